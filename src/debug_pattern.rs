@@ -0,0 +1,113 @@
+use crate::dump_tree::DumpNode;
+use crate::error::WasmError;
+use crate::wasm_lang::{WasmDoc, WasmLang};
+use crate::{convert_to_debug_node, Node};
+
+use ast_grep_core::language::Language;
+use ast_grep_core::meta_var::MetaVariable;
+use ast_grep_core::{AstGrep, Matcher, Pattern, PatternError};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PatternInfo {
+  /// the node that is actually matched, after unwrapping single child nodes
+  tree: DumpNode,
+  meta_variables: Vec<String>,
+  /// None means the pattern can match any kind, e.g. a lone metavariable
+  candidate_kinds: Option<Vec<String>>,
+}
+
+// mirror ast-grep-core's unwrapping of pattern roots
+fn is_single_node(n: &Node) -> bool {
+  match n.get_ts_node().child_count() {
+    1 => true,
+    2 => n.child(1).map_or(false, |c| c.get_ts_node().is_missing()),
+    _ => false,
+  }
+}
+
+fn display_var(var: MetaVariable) -> String {
+  match var {
+    MetaVariable::Named(name, _) => format!("${name}"),
+    MetaVariable::NamedEllipsis(name) => format!("$$${name}"),
+    MetaVariable::Anonymous(_) => "$_".into(),
+    MetaVariable::Ellipsis => "$$$".into(),
+  }
+}
+
+impl PatternInfo {
+  pub fn new(src: &str, lang: WasmLang, timeout: f64) -> Result<Self, JsError> {
+    let processed = lang.pre_process_pattern(src);
+    let mut doc = WasmDoc::new(processed.into_owned(), lang);
+    doc.set_timeout(timeout);
+    // parse once, the clone shares the tree kept by pre_parse with the pattern
+    doc.pre_parse().map_err(WasmError::from)?;
+    let root = AstGrep::doc(doc.clone());
+    let mut goal = root.root();
+    // mirror the checks of Pattern::try_new
+    if goal.get_ts_node().child_count() == 0 {
+      return Err(PatternError::NoContent(src.into()).into());
+    }
+    if !is_single_node(&goal) {
+      return Err(PatternError::MultipleNode(src.into()).into());
+    }
+    let pattern = Pattern::doc(doc);
+    while is_single_node(&goal) {
+      match goal.child(0) {
+        Some(child) => goal = child,
+        None => break,
+      }
+    }
+    let mut meta_variables = vec![];
+    for node in goal.dfs() {
+      if let Some(var) = lang.extract_meta_var(&node.text()) {
+        let var = display_var(var);
+        if !meta_variables.contains(&var) {
+          meta_variables.push(var);
+        }
+      }
+    }
+    let ts_lang = lang.get_ts_language();
+    let candidate_kinds = pattern.potential_kinds().map(|kinds| {
+      kinds
+        .iter()
+        .filter_map(|id| ts_lang.node_kind_for_id(id as u16))
+        .map(|kind| kind.to_string())
+        .collect()
+    });
+    Ok(Self {
+      tree: convert_to_debug_node(goal)?,
+      meta_variables,
+      candidate_kinds,
+    })
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::wasm_lang::set_rust_lang;
+
+  fn debug(src: &str) -> PatternInfo {
+    set_rust_lang();
+    PatternInfo::new(src, WasmLang::Rust, 0.0).expect("should parse")
+  }
+
+  #[test]
+  fn test_pattern_info() {
+    let info = debug("$A.unwrap($$$ARGS, $_)");
+    assert_eq!(info.meta_variables, ["$A", "$$$ARGS", "$_"]);
+    assert_eq!(info.candidate_kinds, Some(vec!["call_expression".into()]));
+    let tree = serde_json::to_value(&info.tree).expect("should serialize");
+    assert_eq!(tree["kind"], "call_expression");
+  }
+
+  #[test]
+  fn test_any_kind() {
+    let info = debug("$A");
+    assert_eq!(info.meta_variables, ["$A"]);
+    assert!(info.candidate_kinds.is_none());
+  }
+}
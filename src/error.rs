@@ -0,0 +1,63 @@
+use ast_grep_core::source::TSParseError;
+
+/// Errors raised by the binding itself, reported to JS instead of panicking.
+/// Errors from rule deserialization and compilation keep their own types.
+#[derive(Debug)]
+pub enum WasmError {
+  /// `setupParser` has not loaded a parser yet.
+  ParserNotReady,
+  /// `fixErrors` is called with a rule without `fix`.
+  MissingFix,
+  LanguageMismatch {
+    rule: &'static str,
+    current: &'static str,
+  },
+  /// tree-sitter gives up parsing when the timeout expires.
+  ParseTimeout,
+  Parse(TSParseError),
+  EmptyTree,
+}
+
+impl std::error::Error for WasmError {}
+
+impl std::fmt::Display for WasmError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::ParserNotReady => write!(f, "Parser is not loaded. Please call setupParser first."),
+      Self::MissingFix => write!(f, "fix is required for rewriting."),
+      Self::LanguageMismatch { rule, current } => write!(
+        f,
+        "Rule language {rule} does not match the current language {current}."
+      ),
+      Self::ParseTimeout => write!(f, "Parsing is cancelled due to timeout."),
+      Self::Parse(e) => write!(f, "{e}"),
+      Self::EmptyTree => write!(f, "Parsed tree has no node."),
+    }
+  }
+}
+
+impl From<TSParseError> for WasmError {
+  fn from(e: TSParseError) -> Self {
+    match e {
+      TSParseError::TreeUnavailable => Self::ParseTimeout,
+      e => Self::Parse(e),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_display() {
+    let e = WasmError::LanguageMismatch {
+      rule: "rust",
+      current: "javascript",
+    };
+    let msg = "Rule language rust does not match the current language javascript.";
+    assert_eq!(e.to_string(), msg);
+    let e = WasmError::from(TSParseError::TreeUnavailable);
+    assert!(matches!(e, WasmError::ParseTimeout));
+  }
+}
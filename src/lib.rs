@@ -1,21 +1,38 @@
+mod debug_pattern;
 mod dump_tree;
-mod utils;
+mod error;
+mod referent_rule;
+mod rule_test;
+mod suppression;
+mod transform;
+mod tree_stats;
+pub mod utils;
 mod wasm_lang;
 
-use wasm_lang::{WasmDoc, WasmLang};
+use wasm_lang::{WasmDoc, WasmLang, Wrapper};
 
-use ast_grep_config::{RuleWithConstraint, SerializableRuleCore};
+use ast_grep_config::SerializableRuleCore;
 use ast_grep_core::language::Language;
-use ast_grep_core::replacer::Fixer;
+use ast_grep_core::replacer::{Fixer, Replacer};
+use ast_grep_core::traversal::Visitor;
 use ast_grep_core::{AstGrep, Node as SgNode};
+use debug_pattern::PatternInfo;
 use dump_tree::{dump_one_node, DumpNode};
-use utils::WasmMatch;
+use error::WasmError;
+use rule_test::TestCase;
+use suppression::Suppressions;
+use transform::RuleWithConvert;
+use tree_stats::TreeStats;
+use utils::{LabelConfig, RuleMeta, Severity, WasmMatch, WasmNode};
 
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use serde_wasm_bindgen::from_value as from_js_val;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryFrom;
-use std::collections::HashMap;
+use std::rc::Rc;
+use std::str::FromStr;
 use tree_sitter as ts;
 use wasm_bindgen::prelude::*;
 
@@ -31,25 +48,103 @@ pub struct WASMConfig {
   constraints: Option<Value>,
   transform: Option<Value>,
   utils: Option<Value>,
+  language: Option<String>,
+  labels: Option<BTreeMap<String, LabelConfig>>,
+  id: Option<String>,
+  severity: Option<Severity>,
+  message: Option<String>,
+  note: Option<String>,
+  url: Option<String>,
+}
+
+impl WASMConfig {
+  fn is_off(&self) -> bool {
+    self.severity == Some(Severity::Off)
+  }
+
+  fn meta(&self) -> RuleMeta {
+    RuleMeta {
+      id: self.id.clone(),
+      severity: self.severity,
+      message: self.message.clone(),
+      note: self.note.clone(),
+      url: self.url.clone(),
+    }
+  }
 }
 
 impl TryFrom<JsValue> for WASMConfig {
   type Error = JsError;
   fn try_from(value: JsValue) -> Result<Self, Self::Error> {
-    Ok(from_js_val(value)?)
+    // config can be either a YAML rule file or a parsed rule object
+    if let Some(yaml) = value.as_string() {
+      Ok(ast_grep_config::from_str(&yaml)?)
+    } else {
+      Ok(from_js_val(value)?)
+    }
   }
 }
 
+thread_local! {
+  // the playground compiles the same rule again on every edit, keep the last one
+  static LAST_MATCHER: RefCell<Option<(String, Rc<RuleWithConvert>)>> = const { RefCell::new(None) };
+}
+
+fn clear_matcher_cache() {
+  LAST_MATCHER.with(|cache| cache.borrow_mut().take());
+}
+
 impl WASMConfig {
-  fn into_matcher(self, lang: WasmLang) -> Result<RuleWithConstraint<WasmLang>, JsError> {
+  fn matcher_key(&self, lang: WasmLang) -> String {
+    let key = serde_json::json!([
+      lang.name(),
+      self.language,
+      self.rule,
+      self.constraints,
+      self.transform,
+      self.utils,
+    ]);
+    key.to_string()
+  }
+
+  fn into_matcher(self, lang: WasmLang) -> Result<Rc<RuleWithConvert>, JsError> {
+    let key = self.matcher_key(lang);
+    let cached = LAST_MATCHER.with(|cache| match &*cache.borrow() {
+      Some((k, matcher)) if *k == key => Some(matcher.clone()),
+      _ => None,
+    });
+    if let Some(matcher) = cached {
+      return Ok(matcher);
+    }
+    let matcher = Rc::new(self.compile(lang)?);
+    LAST_MATCHER.with(|cache| *cache.borrow_mut() = Some((key, matcher.clone())));
+    Ok(matcher)
+  }
+
+  fn compile(self, lang: WasmLang) -> Result<RuleWithConvert, JsError> {
+    if let Some(name) = &self.language {
+      let rule_lang = WasmLang::from_str(name)?;
+      if rule_lang != lang {
+        return Err(
+          WasmError::LanguageMismatch {
+            rule: rule_lang.name(),
+            current: lang.name(),
+          }
+          .into(),
+        );
+      }
+    }
+    referent_rule::check_utils(&self.rule, self.constraints.as_ref(), self.utils.as_ref())?;
+    let (transform, converts) = transform::split_convert(self.transform)?;
     let config = SerializableRuleCore {
       language: lang,
       rule: serde_json::from_value(self.rule)?,
       constraints: self.constraints.map(serde_json::from_value).transpose()?,
-      transform: self.transform.map(serde_json::from_value).transpose()?,
+      transform: transform.map(serde_json::from_value).transpose()?,
       utils: self.utils.map(serde_json::from_value).transpose()?,
     };
-    Ok(config.get_matcher(&Default::default())?)
+    let matcher = config.get_matcher(&Default::default())?;
+    Ok(RuleWithConvert::new(matcher, converts))
   }
 }
 
@@ -60,71 +155,576 @@ pub async fn initialize_tree_sitter() -> Result<(), JsError> {
 
 #[wasm_bindgen(js_name = setupParser)]
 pub async fn setup_parser(lang_name: String, parser_path: String) -> Result<(), JsError> {
-  WasmLang::set_current(&lang_name, &parser_path).await
+  WasmLang::set_current(&lang_name, &parser_path).await?;
+  // kind ids of cached rules may not match the new parser
+  clear_matcher_cache();
+  Ok(())
+}
+
+#[wasm_bindgen(js_name = detectLanguage)]
+pub fn detect_language(
+  path: String,
+  src: String,
+  overrides: JsValue,
+) -> Result<Option<String>, JsError> {
+  let overrides: Option<HashMap<String, String>> = serde_wasm_bindgen::from_value(overrides)?;
+  let overrides = overrides
+    .unwrap_or_default()
+    .into_iter()
+    .map(|(key, lang)| Ok((key, WasmLang::from_str(&lang)?)))
+    .collect::<Result<_, JsError>>()?;
+  let lang = wasm_lang::detect_language(&path, &src, &overrides);
+  Ok(lang.map(|lang| lang.name().to_string()))
+}
+
+#[wasm_bindgen(js_name = supportedLanguages)]
+pub fn supported_languages() -> Vec<JsValue> {
+  WasmLang::ALL.iter().map(|l| l.name().into()).collect()
+}
+
+// AstGrep::doc panics if tree-sitter gives up parsing, e.g. the timeout expires.
+// Parse upfront to report it as an error instead, AstGrep::doc reuses the tree.
+// `timeout` is in microseconds, zero means no limit.
+fn try_parse(src: String, lang: WasmLang, timeout: f64) -> Result<AstGrep<WasmDoc>, WasmError> {
+  let mut doc = WasmDoc::new(src, lang);
+  doc.set_timeout(timeout);
+  doc.pre_parse()?;
+  Ok(AstGrep::doc(doc))
 }
 
 #[wasm_bindgen(js_name = findNodes)]
-pub fn find_nodes(src: String, config: JsValue) -> Result<JsValue, JsError> {
-  let lang = WasmLang::get_current();
+pub fn find_nodes(
+  src: String,
+  config: JsValue,
+  timeout_micros: Option<f64>,
+) -> Result<JsValue, JsError> {
+  let lang = WasmLang::get_current()?;
   let config = WASMConfig::try_from(config)?;
-  let finder = config.into_matcher(lang)?;
-  let root = lang.ast_grep(src);
-  let ret: Vec<_> = root.root().find_all(finder).map(WasmMatch::from).collect();
+  let ret = find_matches(src, config, lang, timeout_micros.unwrap_or_default())?;
   let ret = serde_wasm_bindgen::to_value(&ret)?;
   Ok(ret)
 }
 
-#[wasm_bindgen(js_name = fixErrors)]
-pub fn fix_errors(src: String, config: JsValue) -> Result<String, JsError> {
-  let lang = WasmLang::get_current();
-  let mut config = WASMConfig::try_from(config)?;
-  let fixer = config
-    .fix
-    .take()
-    .expect_throw("fix is required for rewriting");
-  let fixer = if let Some(val) = &config.transform {
+fn find_matches(
+  src: String,
+  config: WASMConfig,
+  lang: WasmLang,
+  timeout: f64,
+) -> Result<Vec<WasmMatch>, JsError> {
+  Ok(scan(src, config, lang, timeout)?.0)
+}
+
+#[wasm_bindgen(js_name = findUnusedSuppressions)]
+pub fn find_unused_suppressions(
+  src: String,
+  config: JsValue,
+  timeout_micros: Option<f64>,
+) -> Result<JsValue, JsError> {
+  let lang = WasmLang::get_current()?;
+  let config = WASMConfig::try_from(config)?;
+  let (_, unused) = scan(src, config, lang, timeout_micros.unwrap_or_default())?;
+  let ret = serde_wasm_bindgen::to_value(&unused)?;
+  Ok(ret)
+}
+
+/// Return matches not suppressed by `ast-grep-ignore` comments, and the comments suppressing nothing.
+fn scan(
+  src: String,
+  mut config: WASMConfig,
+  lang: WasmLang,
+  timeout: f64,
+) -> Result<(Vec<WasmMatch>, Vec<WasmNode>), JsError> {
+  if config.is_off() {
+    return Ok((vec![], vec![]));
+  }
+  let meta = config.meta();
+  let id = meta.id.as_deref();
+  let labels = config.labels.take().unwrap_or_default();
+  // message is interpolated with the same template as fix
+  let message = match &meta.message {
+    Some(msg) => Some(make_fixer(msg, config.transform.as_ref(), lang)?),
+    None => None,
+  };
+  let finder = config.into_matcher(lang)?;
+  let root = try_parse(src, lang, timeout)?;
+  let mut suppressions = Suppressions::new(root.root());
+  let ret = root
+    .root()
+    .find_all(&*finder)
+    .filter(|nm| !suppressions.suppress(id, nm.start_pos()))
+    .map(|nm| {
+      let msg = message.as_ref().map(|m| m.generate_replacement(&nm));
+      let labels = utils::get_labels(&nm, &labels);
+      let mut ret = WasmMatch::from(nm).with_meta(&meta);
+      ret.labels = labels;
+      if let Some(msg) = msg {
+        ret.message = Some(msg.into_iter().collect());
+      }
+      ret
+    })
+    .collect();
+  Ok((ret, suppressions.unused(id)))
+}
+
+fn make_fixer(
+  template: &str,
+  transform: Option<&Value>,
+  lang: WasmLang,
+) -> Result<Fixer<Wrapper>, JsError> {
+  if let Some(val) = transform {
     let map: HashMap<String, Value> = serde_json::from_value(val.clone())?;
     let keys: Vec<_> = map.keys().cloned().collect();
-    Fixer::with_transform(&fixer, &lang, &keys)
+    Ok(Fixer::with_transform(template, &lang, &keys))
   } else {
-    Fixer::try_new(&fixer, &lang)?
+    Ok(Fixer::try_new(template, &lang)?)
+  }
+}
+
+#[wasm_bindgen(js_name = testRule)]
+pub fn test_rule(
+  config: JsValue,
+  test_case: JsValue,
+  timeout_micros: Option<f64>,
+) -> Result<JsValue, JsError> {
+  let lang = WasmLang::get_current()?;
+  let mut config = WASMConfig::try_from(config)?;
+  let test_case: TestCase = from_js_val(test_case)?;
+  let fixer = match config.fix.take() {
+    Some(fix) => Some(make_fixer(&fix, config.transform.as_ref(), lang)?),
+    None => None,
   };
-  let doc = WasmDoc::new(src.clone(), lang);
-  let root = AstGrep::doc(doc);
+  let matcher = config.into_matcher(lang)?;
+  let timeout = timeout_micros.unwrap_or_default();
+  let ret = rule_test::run_test(&matcher, fixer.as_ref(), &test_case, lang, timeout)?;
+  let ret = serde_wasm_bindgen::to_value(&ret)?;
+  Ok(ret)
+}
+
+#[wasm_bindgen(js_name = fixErrors)]
+pub fn fix_errors(
+  src: String,
+  config: JsValue,
+  timeout_micros: Option<f64>,
+) -> Result<String, JsError> {
+  let lang = WasmLang::get_current()?;
+  let config = WASMConfig::try_from(config)?;
+  apply_fix(src, config, lang, timeout_micros.unwrap_or_default())
+}
+
+fn apply_fix(
+  src: String,
+  mut config: WASMConfig,
+  lang: WasmLang,
+  timeout: f64,
+) -> Result<String, JsError> {
+  if config.is_off() {
+    return Ok(src);
+  }
+  let fixer = config.fix.take().ok_or(WasmError::MissingFix)?;
+  let fixer = make_fixer(&fixer, config.transform.as_ref(), lang)?;
+  let id = config.id.take();
   let finder = config.into_matcher(lang)?;
-  let edits: Vec<_> = root.root().replace_all(finder, fixer);
-  let mut new_content = Vec::<char>::new();
+  rewrite(src, &finder, &fixer, id.as_deref(), lang, timeout)
+}
+
+/// Fix matches not suppressed by `ast-grep-ignore` comments targeting rule `id`.
+fn rewrite(
+  src: String,
+  finder: &RuleWithConvert,
+  fixer: &Fixer<Wrapper>,
+  id: Option<&str>,
+  lang: WasmLang,
+  timeout: f64,
+) -> Result<String, JsError> {
+  let mut new_content = String::with_capacity(src.len());
+  let chars: Vec<_> = src.chars().collect();
+  let root = try_parse(src, lang, timeout)?;
+  let mut suppressions = Suppressions::new(root.root());
+  // same traversal as replace_all, which skips matches nested in another match
+  let edits: Vec<_> = Visitor::new(finder)
+    .reentrant(false)
+    .visit(root.root())
+    .filter(|nm| !suppressions.suppress(id, nm.start_pos()))
+    .map(|nm| nm.make_edit(finder, fixer))
+    .collect();
   let mut start = 0;
-  let src: Vec<_> = src.chars().collect();
   for edit in edits {
-    new_content.extend(&src[start..edit.position]);
+    new_content.extend(&chars[start..edit.position]);
     new_content.extend(&edit.inserted_text);
     start = edit.position + edit.deleted_length;
   }
   // add trailing statements
-  new_content.extend(&src[start..]);
-  Ok(new_content.into_iter().collect())
+  new_content.extend(&chars[start..]);
+  Ok(new_content)
 }
 
-fn convert_to_debug_node(n: Node) -> DumpNode {
+fn convert_to_debug_node(n: Node) -> Result<DumpNode, WasmError> {
   let mut cursor = n.get_ts_node().walk();
   let mut target = vec![];
   dump_one_node(&mut cursor, &mut target);
-  target.pop().expect_throw("found empty node")
+  target.pop().ok_or(WasmError::EmptyTree)
 }
 
 #[wasm_bindgen(js_name = dumpASTNodes)]
-pub fn dump_ast_nodes(src: String) -> Result<JsValue, JsError> {
-  let lang = WasmLang::get_current();
-  let doc = WasmDoc::new(src, lang);
-  let root = AstGrep::doc(doc);
-  let debug_node = convert_to_debug_node(root.root());
+pub fn dump_ast_nodes(src: String, timeout_micros: Option<f64>) -> Result<JsValue, JsError> {
+  let lang = WasmLang::get_current()?;
+  let root = try_parse(src, lang, timeout_micros.unwrap_or_default())?;
+  let debug_node = convert_to_debug_node(root.root())?;
   let ret = serde_wasm_bindgen::to_value(&debug_node)?;
   Ok(ret)
 }
 
+#[wasm_bindgen(js_name = treeStats)]
+pub fn tree_stats(src: String, timeout_micros: Option<f64>) -> Result<JsValue, JsError> {
+  let lang = WasmLang::get_current()?;
+  let mut doc = WasmDoc::new(src, lang);
+  doc.set_timeout(timeout_micros.unwrap_or_default());
+  let start = tree_stats::timestamp();
+  doc.pre_parse().map_err(WasmError::from)?;
+  let parse_time = tree_stats::timestamp() - start;
+  let root = AstGrep::doc(doc);
+  let stats = TreeStats::new(root.root().get_ts_node(), parse_time);
+  let ret = serde_wasm_bindgen::to_value(&stats)?;
+  Ok(ret)
+}
+
 #[wasm_bindgen(js_name = preProcessPattern)]
 pub fn pre_process_pattern(query: String) -> Result<String, JsError> {
-  let lang = WasmLang::get_current();
+  let lang = WasmLang::get_current()?;
   Ok(lang.pre_process_pattern(&query).into())
 }
+
+#[wasm_bindgen(js_name = debugPattern)]
+pub fn debug_pattern(pattern: String, timeout_micros: Option<f64>) -> Result<JsValue, JsError> {
+  let lang = WasmLang::get_current()?;
+  let info = PatternInfo::new(&pattern, lang, timeout_micros.unwrap_or_default())?;
+  let ret = serde_wasm_bindgen::to_value(&info)?;
+  Ok(ret)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use wasm_lang::set_rust_lang;
+
+  fn find(src: &str, yaml: &str) -> Vec<String> {
+    set_rust_lang();
+    let config: WASMConfig = ast_grep_config::from_str(yaml).expect("should parse");
+    let matcher = config.into_matcher(WasmLang::Rust).expect("should compile");
+    let root = AstGrep::doc(WasmDoc::new(src.into(), WasmLang::Rust));
+    let ret: Vec<_> = root
+      .root()
+      .find_all(&*matcher)
+      .map(|m| m.text().to_string())
+      .collect();
+    ret
+  }
+
+  #[test]
+  fn test_yaml_rule() {
+    let yaml = r"
+id: no-unwrap
+language: Rust
+rule:
+  pattern: $A.unwrap()
+fix: $A.expect()
+";
+    let ret = find("fn test() { a.unwrap(); b.expect(); }", yaml);
+    assert_eq!(ret, ["a.unwrap()"]);
+  }
+
+  #[test]
+  fn test_yaml_atomic_rules() {
+    let yaml = "rule: { kind: integer_literal }";
+    assert_eq!(find("let a = 1 + 2;", yaml), ["1", "2"]);
+    let yaml = "rule: { kind: identifier, regex: ^test }";
+    assert_eq!(find("let test_a = tester;", yaml), ["test_a", "tester"]);
+  }
+
+  #[test]
+  fn test_composite_rules() {
+    let src = "fn test() { a.unwrap(); b.expect(); c.unwrap_or(1); }";
+    let yaml = r"
+rule:
+  any:
+    - pattern: $A.unwrap()
+    - pattern: $A.expect()
+";
+    assert_eq!(find(src, yaml), ["a.unwrap()", "b.expect()"]);
+    let yaml = r"
+rule:
+  all:
+    - kind: call_expression
+    - not: { pattern: $A.unwrap() }
+    - not: { pattern: $A.unwrap_or($B) }
+";
+    assert_eq!(find(src, yaml), ["b.expect()"]);
+  }
+
+  #[test]
+  fn test_matches_rule() {
+    let yaml = r"
+utils:
+  is-unwrap:
+    pattern: $A.unwrap()
+rule:
+  any:
+    - matches: is-unwrap
+    - pattern: $A.expect()
+";
+    let src = "fn test() { a.unwrap(); b.expect(); c.unwrap_or(1); }";
+    assert_eq!(find(src, yaml), ["a.unwrap()", "b.expect()"]);
+    // recursion through relational rules terminates
+    let yaml = r"
+utils:
+  nested:
+    any:
+      - kind: integer_literal
+      - has: { matches: nested }
+rule:
+  kind: arguments
+  matches: nested
+";
+    let src = "fn test() { a(1); b(c(2)); d(e); }";
+    assert_eq!(find(src, yaml), ["(1)", "(c(2))", "(2)"]);
+  }
+
+  #[test]
+  fn test_constraints() {
+    let src = "fn test() { lodash.map(); lodash_fp.map(); other.map(); }";
+    let yaml = r"
+rule:
+  pattern: $MOD.map()
+constraints:
+  MOD:
+    regex: ^lodash
+";
+    assert_eq!(find(src, yaml), ["lodash.map()", "lodash_fp.map()"]);
+    let yaml = r"
+rule:
+  pattern: let $A = $B;
+constraints:
+  B:
+    kind: integer_literal
+";
+    assert_eq!(find("let a = 1; let b = c;", yaml), ["let a = 1;"]);
+  }
+
+  fn fix(src: &str, yaml: &str) -> String {
+    set_rust_lang();
+    let config: WASMConfig = ast_grep_config::from_str(yaml).expect("should parse");
+    apply_fix(src.into(), config, WasmLang::Rust, 0.0).expect("should fix")
+  }
+
+  #[test]
+  fn test_convert_transform() {
+    let yaml = r"
+rule:
+  pattern: let $A = $B;
+transform:
+  NAME:
+    convert:
+      source: $A
+      toCase: snakeCase
+fix: let $NAME = $B;
+";
+    assert_eq!(fix("let userId = 1;", yaml), "let user_id = 1;");
+  }
+
+  #[test]
+  fn test_convert_chain() {
+    let yaml = r"
+rule:
+  pattern: 'const $A: i32 = $B;'
+transform:
+  TAIL:
+    substring:
+      source: $A
+      startChar: 4
+  UPPER:
+    convert:
+      source: $TAIL
+      toCase: upperCase
+  NAME:
+    convert:
+      source: $UPPER
+      toCase: camelCase
+fix: 'const $UPPER: i32 = $B; // $NAME'
+";
+    let ret = fix("const old_max_len: i32 = 1;", yaml);
+    assert_eq!(ret, "const MAX_LEN: i32 = 1; // maxLen");
+  }
+
+  #[test]
+  fn test_rule_meta() {
+    let yaml = r"
+id: no-unwrap
+severity: warning
+message: Avoid unwrap
+note: Use expect instead
+url: https://example.com/no-unwrap
+rule:
+  pattern: $A.unwrap()
+";
+    let config: WASMConfig = ast_grep_config::from_str(yaml).expect("should parse");
+    assert!(!config.is_off());
+    let meta = config.meta();
+    set_rust_lang();
+    let matcher = config.into_matcher(WasmLang::Rust).expect("should compile");
+    let root = AstGrep::doc(WasmDoc::new("a.unwrap()".into(), WasmLang::Rust));
+    let nm = root.root().find(&*matcher).expect("should match");
+    let ret = serde_json::to_value(WasmMatch::from(nm).with_meta(&meta)).expect("should serialize");
+    assert_eq!(ret["id"], "no-unwrap");
+    assert_eq!(ret["severity"], "warning");
+    assert_eq!(ret["message"], "Avoid unwrap");
+    assert_eq!(ret["note"], "Use expect instead");
+    assert_eq!(ret["url"], "https://example.com/no-unwrap");
+  }
+
+  #[test]
+  fn test_severity_off() {
+    let yaml = r"
+severity: off
+rule:
+  pattern: $A.unwrap()
+fix: $A.expect()
+";
+    assert_eq!(fix("a.unwrap()", yaml), "a.unwrap()");
+    let config: WASMConfig =
+      ast_grep_config::from_str("rule: {kind: identifier}").expect("should parse");
+    assert!(!config.is_off());
+    set_rust_lang();
+    let ret = find_matches("a".into(), config, WasmLang::Rust, 0.0).expect("should find");
+    let ret = serde_json::to_value(&ret[0]).expect("should serialize");
+    assert_eq!(ret.as_object().map(|m| m.len()), Some(2));
+  }
+
+  #[test]
+  fn test_message_interpolation() {
+    let yaml = r"
+message: Do not call $FUNC inside $NAME
+rule:
+  pattern: $FUNC()
+  inside:
+    kind: function_item
+    has: { field: name, pattern: $NAME }
+    stopBy: end
+transform:
+  LOUD:
+    convert: { source: $FUNC, toCase: upperCase }
+";
+    set_rust_lang();
+    let config: WASMConfig = ast_grep_config::from_str(yaml).expect("should parse");
+    let src = "fn render() { alert(); }";
+    let ret = find_matches(src.into(), config, WasmLang::Rust, 0.0).expect("should find");
+    assert_eq!(ret.len(), 1);
+    let msg = ret[0].message.as_deref();
+    assert_eq!(msg, Some("Do not call alert inside render"));
+    let yaml = yaml.replace("call $FUNC", "call $LOUD");
+    let config: WASMConfig = ast_grep_config::from_str(&yaml).expect("should parse");
+    let ret = find_matches(src.into(), config, WasmLang::Rust, 0.0).expect("should find");
+    let msg = ret[0].message.as_deref();
+    assert_eq!(msg, Some("Do not call ALERT inside render"));
+  }
+
+  #[test]
+  fn test_labels() {
+    let yaml = r"
+rule:
+  pattern: let $A = $B;
+  follows:
+    pattern: let $A = $C;
+    stopBy: end
+labels:
+  A:
+    style: primary
+    message: duplicate declaration
+  C:
+    style: secondary
+  D:
+    style: secondary
+";
+    set_rust_lang();
+    let config: WASMConfig = ast_grep_config::from_str(yaml).expect("should parse");
+    let src = "fn test() { let a = 1; let a = 2; }";
+    let ret = find_matches(src.into(), config, WasmLang::Rust, 0.0).expect("should find");
+    assert_eq!(ret.len(), 1);
+    let labels = &ret[0].labels;
+    assert_eq!(labels.len(), 2);
+    assert_eq!(labels[0].node.text, "a");
+    assert_eq!(labels[0].message.as_deref(), Some("duplicate declaration"));
+    assert_eq!(labels[1].node.text, "1");
+    assert_eq!(labels[1].node.range, (0, 20, 0, 21));
+    assert!(matches!(labels[1].style, utils::LabelStyle::Secondary));
+  }
+
+  #[test]
+  fn test_suppression() {
+    let yaml = "{ id: no-unwrap, rule: { pattern: $A.unwrap() } }";
+    let src = r"
+fn test() {
+  // ast-grep-ignore
+  a.unwrap();
+  // ast-grep-ignore: other-rule
+  b.unwrap();
+  c.unwrap(); // ast-grep-ignore: no-unwrap
+  d.unwrap();
+}";
+    set_rust_lang();
+    let config: WASMConfig = ast_grep_config::from_str(yaml).expect("should parse");
+    let (ret, unused) = scan(src.into(), config, WasmLang::Rust, 0.0).expect("should scan");
+    let ret: Vec<_> = ret.iter().map(|m| m.node.text.as_str()).collect();
+    assert_eq!(ret, ["b.unwrap()", "d.unwrap()"]);
+    assert!(unused.is_empty());
+    let src = "// ast-grep-ignore: no-unwrap\nlet a = 1;";
+    let config: WASMConfig = ast_grep_config::from_str(yaml).expect("should parse");
+    let (_, unused) = scan(src.into(), config, WasmLang::Rust, 0.0).expect("should scan");
+    assert_eq!(unused.len(), 1);
+    // a comment above a function does not hide findings in its body
+    let src = "// ast-grep-ignore\nfn test() {\n  a.unwrap();\n  b.unwrap();\n}";
+    let config: WASMConfig = ast_grep_config::from_str(yaml).expect("should parse");
+    let (ret, unused) = scan(src.into(), config, WasmLang::Rust, 0.0).expect("should scan");
+    assert_eq!(ret.len(), 2);
+    assert_eq!(unused.len(), 1);
+  }
+
+  #[test]
+  fn test_fix_suppression() {
+    let yaml = "{ id: no-unwrap, rule: { pattern: $A.unwrap() }, fix: $A.expect() }";
+    let src =
+      "a.unwrap();\n// ast-grep-ignore: no-unwrap\nb.unwrap();\nc.unwrap(); // ast-grep-ignore";
+    let expected =
+      "a.expect();\n// ast-grep-ignore: no-unwrap\nb.unwrap();\nc.unwrap(); // ast-grep-ignore";
+    assert_eq!(fix(src, yaml), expected);
+  }
+
+  #[test]
+  fn test_parse_timeout() {
+    set_rust_lang();
+    let src = "fn test() { a.unwrap(); }\n".repeat(10000);
+    let ret = try_parse(src.clone(), WasmLang::Rust, 1.0);
+    assert!(matches!(ret, Err(WasmError::ParseTimeout)));
+    // the timeout only applies to its own call
+    let config: WASMConfig =
+      ast_grep_config::from_str("rule: { kind: source_file }").expect("should parse");
+    let ret = find_matches(src, config, WasmLang::Rust, 0.0).expect("should find");
+    assert_eq!(ret.len(), 1);
+  }
+
+  #[test]
+  fn test_matcher_cache() {
+    set_rust_lang();
+    let compile = |yaml: &str| {
+      let config: WASMConfig = ast_grep_config::from_str(yaml).expect("should parse");
+      config.into_matcher(WasmLang::Rust).expect("should compile")
+    };
+    let a = compile("rule: { pattern: $A.unwrap() }");
+    let b = compile("{ rule: { pattern: $A.unwrap() }, fix: $A.expect() }");
+    assert!(Rc::ptr_eq(&a, &b));
+    let c = compile("rule: { pattern: $A.expect() }");
+    assert!(!Rc::ptr_eq(&a, &c));
+    clear_matcher_cache();
+    let d = compile("rule: { pattern: $A.expect() }");
+    assert!(!Rc::ptr_eq(&c, &d));
+  }
+}
@@ -0,0 +1,179 @@
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Debug)]
+pub enum ReferentRuleError {
+  NotFound(String),
+  Cyclic(String),
+}
+
+impl std::error::Error for ReferentRuleError {}
+
+impl std::fmt::Display for ReferentRuleError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::NotFound(id) => write!(f, "Utility rule `{id}` is not found in `utils`."),
+      Self::Cyclic(id) => write!(f, "Utility rule `{id}` has a cyclic dependency."),
+    }
+  }
+}
+
+/// Collect ids referenced by `matches` in a rule, including nested and relational sub-rules.
+/// `same_node` is false for references under relational rules, which match other nodes.
+fn collect_matches<'a>(rule: &'a Value, same_node: bool, refs: &mut Vec<(&'a str, bool)>) {
+  match rule {
+    Value::Object(map) => {
+      for (key, val) in map {
+        match (key.as_str(), val) {
+          ("matches", Value::String(id)) => refs.push((id, same_node)),
+          ("all" | "any" | "not", _) => collect_matches(val, same_node, refs),
+          _ => collect_matches(val, false, refs),
+        }
+      }
+    }
+    Value::Array(rules) => {
+      for rule in rules {
+        collect_matches(rule, same_node, refs);
+      }
+    }
+    _ => (),
+  }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Visit {
+  InProgress,
+  Done,
+}
+
+struct UtilGraph<'a> {
+  deps: HashMap<&'a str, Vec<&'a str>>,
+  visited: HashMap<&'a str, Visit>,
+}
+
+impl<'a> UtilGraph<'a> {
+  fn visit(&mut self, id: &'a str) -> Result<(), ReferentRuleError> {
+    match self.visited.get(id) {
+      Some(Visit::Done) => return Ok(()),
+      Some(Visit::InProgress) => return Err(ReferentRuleError::Cyclic(id.into())),
+      None => (),
+    }
+    let Some(deps) = self.deps.get(id).cloned() else {
+      return Err(ReferentRuleError::NotFound(id.into()));
+    };
+    self.visited.insert(id, Visit::InProgress);
+    for dep in deps {
+      self.visit(dep)?;
+    }
+    self.visited.insert(id, Visit::Done);
+    Ok(())
+  }
+}
+
+/// ast-grep-config resolves `matches` lazily, so unknown ids silently match nothing,
+/// and a util that reaches itself on the same node recurses until the stack overflows.
+/// Recursion through relational rules moves to another node and terminates.
+/// Constraints are checked for unknown ids as well.
+pub fn check_utils(
+  rule: &Value,
+  constraints: Option<&Value>,
+  utils: Option<&Value>,
+) -> Result<(), ReferentRuleError> {
+  let mut refs = vec![];
+  collect_matches(rule, true, &mut refs);
+  if let Some(Value::Object(constraints)) = constraints {
+    for constraint in constraints.values() {
+      collect_matches(constraint, true, &mut refs);
+    }
+  }
+  let mut deps = HashMap::new();
+  if let Some(Value::Object(utils)) = utils {
+    for (id, util) in utils {
+      let start = refs.len();
+      collect_matches(util, true, &mut refs);
+      let same_node = refs[start..].iter().filter(|r| r.1).map(|r| r.0);
+      deps.insert(id.as_str(), same_node.collect());
+    }
+  }
+  if let Some((id, _)) = refs.iter().find(|(id, _)| !deps.contains_key(id)) {
+    return Err(ReferentRuleError::NotFound(id.to_string()));
+  }
+  let ids: Vec<_> = deps.keys().copied().collect();
+  let mut graph = UtilGraph {
+    deps,
+    visited: HashMap::new(),
+  };
+  for id in ids {
+    graph.visit(id)?;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use serde_json::json;
+
+  #[test]
+  fn test_no_utils() {
+    assert!(check_utils(&json!({"pattern": "a"}), None, None).is_ok());
+    let ret = check_utils(&json!({"matches": "a"}), None, None);
+    assert!(matches!(ret, Err(ReferentRuleError::NotFound(id)) if id == "a"));
+  }
+
+  #[test]
+  fn test_nested_reference() {
+    let utils = json!({
+      "a": {"kind": "identifier"},
+      "b": {"inside": {"matches": "a"}},
+    });
+    let rule = json!({"all": [{"has": {"matches": "b"}}]});
+    assert!(check_utils(&rule, None, Some(&utils)).is_ok());
+    let rule = json!({"all": [{"not": {"matches": "c"}}]});
+    let ret = check_utils(&rule, None, Some(&utils));
+    assert!(matches!(ret, Err(ReferentRuleError::NotFound(id)) if id == "c"));
+    let rule = json!({"has": {"stopBy": {"matches": "c"}, "kind": "a"}});
+    let ret = check_utils(&rule, None, Some(&utils));
+    assert!(matches!(ret, Err(ReferentRuleError::NotFound(id)) if id == "c"));
+  }
+
+  #[test]
+  fn test_constraint_reference() {
+    let utils = json!({"a": {"kind": "identifier"}});
+    let rule = json!({"pattern": "$A"});
+    let constraints = json!({"A": {"matches": "a"}});
+    assert!(check_utils(&rule, Some(&constraints), Some(&utils)).is_ok());
+    let constraints = json!({"A": {"any": [{"matches": "missing"}]}});
+    let ret = check_utils(&rule, Some(&constraints), Some(&utils));
+    assert!(matches!(ret, Err(ReferentRuleError::NotFound(id)) if id == "missing"));
+  }
+
+  #[test]
+  fn test_cyclic_utils() {
+    let utils = json!({
+      "a": {"not": {"matches": "b"}},
+      "b": {"all": [{"any": [{"matches": "a"}]}]},
+    });
+    let ret = check_utils(&json!({"matches": "a"}), None, Some(&utils));
+    assert!(matches!(ret, Err(ReferentRuleError::Cyclic(_))));
+    // unused utils are checked as well
+    let ret = check_utils(&json!({"pattern": "a"}), None, Some(&utils));
+    assert!(matches!(ret, Err(ReferentRuleError::Cyclic(_))));
+    let utils = json!({"a": {"matches": "a"}});
+    let ret = check_utils(&json!({"pattern": "a"}), None, Some(&utils));
+    assert!(matches!(ret, Err(ReferentRuleError::Cyclic(id)) if id == "a"));
+  }
+
+  #[test]
+  fn test_relational_recursion() {
+    let utils = json!({
+      "a": {"not": {"matches": "b"}},
+      "b": {"inside": {"any": [{"matches": "a"}]}},
+    });
+    assert!(check_utils(&json!({"matches": "a"}), None, Some(&utils)).is_ok());
+    let utils = json!({
+      "nested": {"any": [{"kind": "integer_literal"}, {"has": {"matches": "nested"}}]},
+    });
+    assert!(check_utils(&json!({"matches": "nested"}), None, Some(&utils)).is_ok());
+  }
+}
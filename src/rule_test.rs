@@ -0,0 +1,154 @@
+use crate::transform::RuleWithConvert;
+use crate::wasm_lang::{WasmLang, Wrapper};
+use crate::{rewrite, try_parse};
+
+use ast_grep_core::replacer::Fixer;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use wasm_bindgen::prelude::*;
+
+#[derive(Serialize, Deserialize)]
+pub struct TestCase {
+  /// snippets that should not match the rule
+  #[serde(default)]
+  pub valid: Vec<String>,
+  /// snippets that should match the rule
+  #[serde(default)]
+  pub invalid: Vec<String>,
+  /// reviewed fix output of invalid snippets, keyed by the snippet
+  #[serde(default)]
+  pub snapshots: BTreeMap<String, String>,
+  /// accept the current fix output instead of comparing with snapshots
+  #[serde(default)]
+  pub update: bool,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TestResult {
+  pub passed: bool,
+  /// valid snippets reported by the rule
+  pub unexpected_matches: Vec<String>,
+  /// invalid snippets not reported by the rule
+  pub missed: Vec<String>,
+  /// fix output of every invalid snippet, to be saved in update mode
+  pub snapshots: BTreeMap<String, String>,
+  /// invalid snippets whose fix output is missing or differs from the reviewed snapshot
+  pub changed_snapshots: Vec<String>,
+}
+
+fn has_match(
+  matcher: &RuleWithConvert,
+  code: &str,
+  lang: WasmLang,
+  timeout: f64,
+) -> Result<bool, JsError> {
+  let root = try_parse(code.to_string(), lang, timeout)?;
+  let found = root.root().find(matcher).is_some();
+  Ok(found)
+}
+
+pub fn run_test(
+  matcher: &RuleWithConvert,
+  fixer: Option<&Fixer<Wrapper>>,
+  case: &TestCase,
+  lang: WasmLang,
+  timeout: f64,
+) -> Result<TestResult, JsError> {
+  let mut result = TestResult::default();
+  for code in &case.valid {
+    if has_match(matcher, code, lang, timeout)? {
+      result.unexpected_matches.push(code.clone());
+    }
+  }
+  for code in &case.invalid {
+    if !has_match(matcher, code, lang, timeout)? {
+      result.missed.push(code.clone());
+    }
+  }
+  if let Some(fixer) = fixer {
+    for code in &case.invalid {
+      let fixed = rewrite(code.clone(), matcher, fixer, None, lang, timeout)?;
+      if !case.update && case.snapshots.get(code) != Some(&fixed) {
+        result.changed_snapshots.push(code.clone());
+      }
+      result.snapshots.insert(code.clone(), fixed);
+    }
+  }
+  result.passed = result.unexpected_matches.is_empty()
+    && result.missed.is_empty()
+    && result.changed_snapshots.is_empty();
+  Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::wasm_lang::set_rust_lang;
+  use crate::WASMConfig;
+
+  fn test_rule(yaml: &str, case: &TestCase) -> TestResult {
+    set_rust_lang();
+    let mut config: WASMConfig = ast_grep_config::from_str(yaml).expect("should parse");
+    let fixer = config.fix.take().map(|fix| {
+      let fixer = crate::make_fixer(&fix, config.transform.as_ref(), WasmLang::Rust);
+      fixer.expect("should create fixer")
+    });
+    let matcher = config.into_matcher(WasmLang::Rust).expect("should compile");
+    run_test(&matcher, fixer.as_ref(), case, WasmLang::Rust, 0.0).expect("should run")
+  }
+
+  fn make_case(valid: &[&str], invalid: &[&str]) -> TestCase {
+    TestCase {
+      valid: valid.iter().map(|s| s.to_string()).collect(),
+      invalid: invalid.iter().map(|s| s.to_string()).collect(),
+      snapshots: BTreeMap::new(),
+      update: false,
+    }
+  }
+
+  #[test]
+  fn test_valid_invalid() {
+    let case = make_case(
+      &["a.expect()", "unwrap()"],
+      &["a.unwrap()", "a.b().unwrap()"],
+    );
+    let ret = test_rule("rule: { pattern: $A.unwrap() }", &case);
+    assert!(ret.passed);
+    assert!(ret.unexpected_matches.is_empty());
+    assert!(ret.missed.is_empty());
+  }
+
+  #[test]
+  fn test_report_failures() {
+    let case = make_case(&["a.expect()", "a.unwrap()"], &["a.unwrap()", "unwrap()"]);
+    let ret = test_rule("rule: { pattern: $A.unwrap() }", &case);
+    assert!(!ret.passed);
+    assert_eq!(ret.unexpected_matches, ["a.unwrap()"]);
+    assert_eq!(ret.missed, ["unwrap()"]);
+  }
+
+  #[test]
+  fn test_fix_snapshots() {
+    let yaml = "{ rule: { pattern: $A.unwrap() }, fix: $A.expect() }";
+    let mut case = make_case(&[], &["a.unwrap()", "b.unwrap()"]);
+    // missing snapshots are reported
+    let ret = test_rule(yaml, &case);
+    assert!(!ret.passed);
+    assert_eq!(ret.changed_snapshots.len(), 2);
+    assert_eq!(ret.snapshots["a.unwrap()"], "a.expect()");
+    case
+      .snapshots
+      .insert("a.unwrap()".into(), "a.expect()".into());
+    case
+      .snapshots
+      .insert("b.unwrap()".into(), "b.unwrap_or()".into());
+    let ret = test_rule(yaml, &case);
+    assert_eq!(ret.changed_snapshots, ["b.unwrap()"]);
+    // update mode accepts the new output
+    case.update = true;
+    let ret = test_rule(yaml, &case);
+    assert!(ret.passed);
+    assert_eq!(ret.snapshots["b.unwrap()"], "b.expect()");
+  }
+}
@@ -0,0 +1,154 @@
+use crate::utils::WasmNode;
+use crate::wasm_lang::WasmDoc;
+use ast_grep_core::Node as SgNode;
+
+type Node<'a> = SgNode<'a, WasmDoc>;
+type Pos = (usize, usize);
+
+const IGNORE: &str = "ast-grep-ignore";
+const COMMENT_STARTS: [&str; 3] = ["//", "/*", "#"];
+
+/// Parse the ids of a comment whose body starts with the marker, None if it is not a suppression.
+fn parse_ids(text: &str) -> Option<Option<Vec<String>>> {
+  let text = text.trim();
+  let body = COMMENT_STARTS
+    .iter()
+    .find_map(|start| text.strip_prefix(start))
+    .unwrap_or(text);
+  let body = body.trim_end_matches("*/").trim();
+  let rest = body.strip_prefix(IGNORE)?.trim_start();
+  if rest.is_empty() {
+    return Some(None);
+  }
+  let ids = rest.strip_prefix(':')?.split(',');
+  let ids = ids
+    .map(|id| id.trim().to_string())
+    .filter(|id| !id.is_empty());
+  Some(Some(ids.collect()))
+}
+
+/// A `// ast-grep-ignore` or `// ast-grep-ignore: id1, id2` comment.
+/// It suppresses matches starting on the next line, like the ast-grep CLI.
+/// A trailing comment suppresses matches starting on its own line instead.
+struct Suppression {
+  comment: WasmNode,
+  /// None suppresses all rules
+  ids: Option<Vec<String>>,
+  /// matches starting on this line are suppressed
+  line: usize,
+  used: bool,
+}
+
+impl Suppression {
+  fn new(node: Node) -> Option<Self> {
+    let text = node.text();
+    let ids = parse_ids(&text)?;
+    let start = node.start_pos().0;
+    let trailing = node.prev().map_or(false, |n| n.end_pos().0 == start);
+    let line = if trailing {
+      start
+    } else {
+      // line comments may include the trailing newline
+      let end = start + text.trim_end().lines().count().saturating_sub(1);
+      end + 1
+    };
+    Some(Self {
+      line,
+      comment: WasmNode::from(node),
+      ids,
+      used: false,
+    })
+  }
+
+  fn applies_to(&self, id: Option<&str>) -> bool {
+    match (&self.ids, id) {
+      (None, _) => true,
+      (Some(ids), Some(id)) => ids.iter().any(|i| i == id),
+      (Some(_), None) => false,
+    }
+  }
+
+  fn covers(&self, start: Pos) -> bool {
+    start.0 == self.line
+  }
+}
+
+pub struct Suppressions(Vec<Suppression>);
+
+impl Suppressions {
+  pub fn new(root: Node) -> Self {
+    let suppressions = root
+      .dfs()
+      .filter(|n| n.kind().contains("comment"))
+      .filter_map(Suppression::new)
+      .collect();
+    Self(suppressions)
+  }
+
+  /// Check if a match of rule `id` starting at `start` is suppressed, and mark the comment used.
+  pub fn suppress(&mut self, id: Option<&str>, start: Pos) -> bool {
+    let mut suppressed = false;
+    for sup in &mut self.0 {
+      if sup.applies_to(id) && sup.covers(start) {
+        sup.used = true;
+        suppressed = true;
+      }
+    }
+    suppressed
+  }
+
+  /// Comments targeting rule `id` that suppress nothing.
+  pub fn unused(self, id: Option<&str>) -> Vec<WasmNode> {
+    self
+      .0
+      .into_iter()
+      .filter(|sup| !sup.used && sup.applies_to(id))
+      .map(|sup| sup.comment)
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::wasm_lang::{set_rust_lang, WasmLang};
+  use ast_grep_core::AstGrep;
+
+  fn get_ids(comment: &str) -> Option<Option<Vec<String>>> {
+    set_rust_lang();
+    let root = AstGrep::doc(WasmDoc::new(comment.into(), WasmLang::Rust));
+    let node = root.root().child(0).expect("should have comment");
+    Suppression::new(node).map(|s| s.ids)
+  }
+
+  #[test]
+  fn test_parse_comment() {
+    assert_eq!(get_ids("// ast-grep-ignore"), Some(None));
+    let ids = get_ids("// ast-grep-ignore: a, b");
+    assert_eq!(ids, Some(Some(vec!["a".into(), "b".into()])));
+    let ids = get_ids("/* ast-grep-ignore: a */");
+    assert_eq!(ids, Some(Some(vec!["a".into()])));
+    assert_eq!(get_ids("/* ast-grep-ignore */"), Some(None));
+    assert_eq!(get_ids("// normal comment"), None);
+    // the marker must start the comment body
+    assert_eq!(get_ids("// TODO: remove ast-grep-ignore usage"), None);
+    assert_eq!(get_ids("// ast-grep-ignored"), None);
+    assert_eq!(get_ids("// ast-grep-ignore this line"), None);
+  }
+
+  #[test]
+  fn test_suppress() {
+    set_rust_lang();
+    let src = "// ast-grep-ignore: a\nfn test() {\n  1;\n}\n// ast-grep-ignore\n";
+    let root = AstGrep::doc(WasmDoc::new(src.into(), WasmLang::Rust));
+    let mut sups = Suppressions::new(root.root());
+    assert!(!sups.suppress(Some("b"), (1, 0)));
+    assert!(!sups.suppress(None, (1, 0)));
+    assert!(sups.suppress(Some("a"), (1, 0)));
+    // the body of the next node is not covered
+    assert!(!sups.suppress(Some("a"), (2, 2)));
+    let unused = sups.unused(Some("a"));
+    assert_eq!(unused.len(), 1);
+    assert_eq!(unused[0].text, "// ast-grep-ignore");
+  }
+}
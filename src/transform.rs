@@ -0,0 +1,315 @@
+use crate::wasm_lang::WasmLang;
+
+use ast_grep_config::RuleWithConstraint;
+use ast_grep_core::language::Language;
+use ast_grep_core::meta_var::MetaVarEnv;
+use ast_grep_core::source::Content;
+use ast_grep_core::{Doc, Matcher, Node};
+use bit_set::BitSet;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// ast-grep-config only supports substring and replace.
+/// Case conversion is computed here after the rule matches.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Convert {
+  source: String,
+  to_case: StringCase,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum StringCase {
+  LowerCase,
+  UpperCase,
+  Capitalize,
+  CamelCase,
+  SnakeCase,
+  KebabCase,
+  PascalCase,
+}
+
+/// Split words at `_`, `-`, whitespace and camel case boundaries.
+/// e.g. `parseHTMLString` => `parse`, `HTML`, `String`
+fn split_words(s: &str) -> Vec<String> {
+  let chars: Vec<_> = s.chars().collect();
+  let mut words = vec![];
+  let mut word = String::new();
+  for (i, &c) in chars.iter().enumerate() {
+    if c == '_' || c == '-' || c.is_whitespace() {
+      if !word.is_empty() {
+        words.push(std::mem::take(&mut word));
+      }
+      continue;
+    }
+    if let Some(&prev) = word.chars().last().as_ref() {
+      let next_lower = chars.get(i + 1).map_or(false, |n| n.is_lowercase());
+      let lower_to_upper = !prev.is_uppercase() && c.is_uppercase();
+      let acronym_end = prev.is_uppercase() && c.is_uppercase() && next_lower;
+      if lower_to_upper || acronym_end {
+        words.push(std::mem::take(&mut word));
+      }
+    }
+    word.push(c);
+  }
+  if !word.is_empty() {
+    words.push(word);
+  }
+  words
+}
+
+fn capitalize(s: &str) -> String {
+  let mut chars = s.chars();
+  match chars.next() {
+    Some(c) => c.to_uppercase().chain(chars).collect(),
+    None => String::new(),
+  }
+}
+
+impl StringCase {
+  fn apply(&self, s: &str) -> String {
+    use StringCase::*;
+    let lower_words = || split_words(s).into_iter().map(|w| w.to_lowercase());
+    match self {
+      LowerCase => s.to_lowercase(),
+      UpperCase => s.to_uppercase(),
+      Capitalize => capitalize(s),
+      CamelCase => lower_words()
+        .enumerate()
+        .map(|(i, w)| if i == 0 { w } else { capitalize(&w) })
+        .collect(),
+      PascalCase => lower_words().map(|w| capitalize(&w)).collect(),
+      SnakeCase => lower_words().collect::<Vec<_>>().join("_"),
+      KebabCase => lower_words().collect::<Vec<_>>().join("-"),
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum TransformError {
+  Convert(serde_json::Error),
+  /// ast-grep-config computes its transforms before converts, so the source would be empty.
+  ConvertSource {
+    key: String,
+    source: String,
+  },
+}
+
+impl std::error::Error for TransformError {}
+
+impl std::fmt::Display for TransformError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Convert(e) => write!(f, "{e}"),
+      Self::ConvertSource { key, source } => write!(
+        f,
+        "Transform `{key}` cannot use `{source}` from convert as source."
+      ),
+    }
+  }
+}
+
+impl From<serde_json::Error> for TransformError {
+  fn from(e: serde_json::Error) -> Self {
+    Self::Convert(e)
+  }
+}
+
+/// Extract `convert` entries from transform, the rest goes to ast-grep-config.
+pub fn split_convert(
+  transform: Option<Value>,
+) -> Result<(Option<Value>, HashMap<String, Convert>), TransformError> {
+  let Some(Value::Object(mut map)) = transform else {
+    return Ok((transform, HashMap::new()));
+  };
+  let keys: Vec<_> = map
+    .iter()
+    .filter(|(_, v)| v.get("convert").is_some())
+    .map(|(k, _)| k.clone())
+    .collect();
+  let mut converts = HashMap::new();
+  for key in keys {
+    let mut val = map.remove(&key).expect("key must exist");
+    let convert = serde_json::from_value(val["convert"].take())?;
+    converts.insert(key, convert);
+  }
+  for (key, val) in &map {
+    let sources = val.as_object().into_iter().flat_map(|t| t.values());
+    for source in sources.filter_map(|t| t.get("source")?.as_str()) {
+      if converts.contains_key(source.trim_start_matches('$')) {
+        return Err(TransformError::ConvertSource {
+          key: key.clone(),
+          source: source.into(),
+        });
+      }
+    }
+  }
+  Ok((Some(Value::Object(map)), converts))
+}
+
+pub struct RuleWithConvert {
+  rule: RuleWithConstraint<WasmLang>,
+  converts: HashMap<String, Convert>,
+  // find_all asks for potential kinds on every match, compute them only once
+  kinds: Option<BitSet>,
+}
+
+impl RuleWithConvert {
+  pub fn new(rule: RuleWithConstraint<WasmLang>, converts: HashMap<String, Convert>) -> Self {
+    let kinds = rule.potential_kinds();
+    Self {
+      rule,
+      converts,
+      kinds,
+    }
+  }
+
+  fn insert<D: Doc<Lang = WasmLang>>(&self, key: &str, env: &mut MetaVarEnv<D>, lang: &WasmLang) {
+    if env.get_transformed(key).is_some() {
+      return;
+    }
+    // avoid cyclic
+    env.insert_transformation(key.to_string(), vec![]);
+    let convert = &self.converts[key];
+    let source = lang.pre_process_pattern(&convert.source);
+    let Some(var) = lang.extract_meta_var(&source) else {
+      return;
+    };
+    if let Some(name) = var_name(&var) {
+      if self.converts.contains_key(name) {
+        self.insert(name, env, lang);
+      }
+    }
+    let Some(bytes) = env.get_var_bytes(&var) else {
+      return;
+    };
+    let text = <D::Source as Content>::encode_bytes(bytes);
+    let converted = convert.to_case.apply(&text);
+    let bytes = <D::Source as Content>::decode_str(&converted).to_vec();
+    env.insert_transformation(key.to_string(), bytes);
+  }
+}
+
+fn var_name(var: &ast_grep_core::meta_var::MetaVariable) -> Option<&str> {
+  use ast_grep_core::meta_var::MetaVariable as MV;
+  match var {
+    MV::Named(name, _) | MV::NamedEllipsis(name) => Some(name),
+    _ => None,
+  }
+}
+
+impl Matcher<WasmLang> for RuleWithConvert {
+  fn match_node_with_env<'tree, D: Doc<Lang = WasmLang>>(
+    &self,
+    node: Node<'tree, D>,
+    env: &mut Cow<MetaVarEnv<'tree, D>>,
+  ) -> Option<Node<'tree, D>> {
+    if let Some(kinds) = &self.kinds {
+      if !kinds.contains(node.kind_id().into()) {
+        return None;
+      }
+    }
+    let ret = self.rule.match_node_with_env(node, env)?;
+    if !self.converts.is_empty() {
+      let lang = *ret.lang();
+      let env = env.to_mut();
+      for key in self.converts.keys() {
+        self.insert(key, env, &lang);
+      }
+    }
+    Some(ret)
+  }
+
+  fn potential_kinds(&self) -> Option<BitSet> {
+    self.kinds.clone()
+  }
+
+  fn get_match_len<D: Doc<Lang = WasmLang>>(&self, node: Node<D>) -> Option<usize> {
+    self.rule.get_match_len(node)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use serde_json::json;
+
+  #[test]
+  fn test_split_words() {
+    assert_eq!(split_words("parseHTMLString"), ["parse", "HTML", "String"]);
+    assert_eq!(split_words("snake_case-kebab"), ["snake", "case", "kebab"]);
+    assert_eq!(split_words("PascalCase2D"), ["Pascal", "Case2", "D"]);
+    assert!(split_words("__").is_empty());
+  }
+
+  #[test]
+  fn test_string_case() {
+    use StringCase::*;
+    let s = "user_accountId";
+    assert_eq!(LowerCase.apply(s), "user_accountid");
+    assert_eq!(UpperCase.apply(s), "USER_ACCOUNTID");
+    assert_eq!(Capitalize.apply(s), "User_accountId");
+    assert_eq!(CamelCase.apply(s), "userAccountId");
+    assert_eq!(PascalCase.apply(s), "UserAccountId");
+    assert_eq!(SnakeCase.apply(s), "user_account_id");
+    assert_eq!(KebabCase.apply(s), "user-account-id");
+  }
+
+  #[test]
+  fn test_split_convert() {
+    let transform = json!({
+      "A": { "substring": { "source": "$B", "startChar": 1 } },
+      "C": { "convert": { "source": "$A", "toCase": "upperCase" } },
+    });
+    let (rest, converts) = split_convert(Some(transform)).expect("should split");
+    assert_eq!(
+      rest,
+      Some(json!({"A": { "substring": { "source": "$B", "startChar": 1 } }}))
+    );
+    assert!(converts.contains_key("C"));
+    let ret = split_convert(Some(json!({ "C": { "convert": { "source": "$A" } } })));
+    assert!(ret.is_err());
+  }
+
+  #[test]
+  fn test_convert_as_source() {
+    let transform = json!({
+      "SNAKE": { "convert": { "source": "$A", "toCase": "snakeCase" } },
+      "TAIL": { "substring": { "source": "$SNAKE", "startChar": 5 } },
+    });
+    let Err(e) = split_convert(Some(transform)) else {
+      panic!("should reject convert source");
+    };
+    let msg = "Transform `TAIL` cannot use `$SNAKE` from convert as source.";
+    assert_eq!(e.to_string(), msg);
+  }
+
+  #[test]
+  fn test_cached_kinds() {
+    use crate::wasm_lang::{set_rust_lang, WasmDoc};
+    use ast_grep_config::SerializableRuleCore;
+    use ast_grep_core::AstGrep;
+    set_rust_lang();
+    let rule = json!({ "any": [{ "kind": "integer_literal" }, { "pattern": "$A.unwrap()" }] });
+    let core = SerializableRuleCore {
+      language: WasmLang::Rust,
+      rule: serde_json::from_value(rule).expect("should parse"),
+      constraints: None,
+      transform: None,
+      utils: None,
+    };
+    let rule = core
+      .get_matcher(&Default::default())
+      .expect("should compile");
+    let expected = rule.potential_kinds();
+    let rule = RuleWithConvert::new(rule, HashMap::new());
+    assert_eq!(rule.potential_kinds(), expected);
+    assert_eq!(rule.potential_kinds().map(|k| k.len()), Some(2));
+    let root = AstGrep::doc(WasmDoc::new("a.unwrap(); 1;".into(), WasmLang::Rust));
+    assert_eq!(root.root().find_all(&rule).count(), 2);
+  }
+}
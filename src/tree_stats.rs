@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+use tree_sitter as ts;
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TreeStats {
+  node_count: usize,
+  max_depth: usize,
+  error_count: usize,
+  /// parse time in milliseconds
+  parse_time: f64,
+}
+
+impl TreeStats {
+  pub fn new(root: ts::Node, parse_time: f64) -> Self {
+    let mut stats = TreeStats {
+      node_count: 0,
+      max_depth: 0,
+      error_count: 0,
+      parse_time,
+    };
+    let mut cursor = root.walk();
+    let mut depth = 0;
+    loop {
+      let node = cursor.node();
+      stats.node_count += 1;
+      stats.max_depth = stats.max_depth.max(depth);
+      if node.is_error() || node.is_missing() {
+        stats.error_count += 1;
+      }
+      if cursor.goto_first_child() {
+        depth += 1;
+        continue;
+      }
+      // go to the next sibling of the closest ancestor that has one
+      while !cursor.goto_next_sibling() {
+        if depth == 0 || !cursor.goto_parent() {
+          return stats;
+        }
+        depth -= 1;
+      }
+    }
+  }
+}
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+extern "C" {
+  #[wasm_bindgen(js_namespace = performance)]
+  fn now() -> f64;
+}
+
+/// current timestamp in milliseconds
+#[cfg(target_arch = "wasm32")]
+pub fn timestamp() -> f64 {
+  now()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn timestamp() -> f64 {
+  use std::time::{SystemTime, UNIX_EPOCH};
+  let elapsed = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .unwrap_or_default();
+  elapsed.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::wasm_lang::{WasmDoc, WasmLang};
+  use ast_grep_core::AstGrep;
+
+  fn get_stats(src: &str) -> TreeStats {
+    crate::wasm_lang::set_rust_lang();
+    let root = AstGrep::doc(WasmDoc::new(src.into(), WasmLang::Rust));
+    TreeStats::new(root.root().get_ts_node(), 0.0)
+  }
+
+  #[test]
+  fn test_count_nodes() {
+    // source_file > expression_statement > integer_literal, ;
+    let stats = get_stats("123;");
+    assert_eq!(stats.node_count, 4);
+    assert_eq!(stats.max_depth, 2);
+    assert_eq!(stats.error_count, 0);
+  }
+
+  #[test]
+  fn test_count_errors() {
+    let stats = get_stats("fn test() { let a = ; }");
+    assert!(stats.error_count > 0);
+    let stats = get_stats("fn test() { let a = 1; }");
+    assert_eq!(stats.error_count, 0);
+  }
+}
@@ -1,7 +1,7 @@
-use crate::wasm_lang::WasmLang;
+use crate::wasm_lang::WasmDoc;
 use ast_grep_core::{
   meta_var::{MetaVarEnv, MetaVariable},
-  Node as SgNode, NodeMatch as SgNodeMatch, StrDoc,
+  Node as SgNode, NodeMatch as SgNodeMatch,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
@@ -17,8 +17,8 @@ pub fn set_panic_hook() {
   console_error_panic_hook::set_once();
 }
 
-type Node<'a> = SgNode<'a, StrDoc<WasmLang>>;
-type NodeMatch<'a> = SgNodeMatch<'a, StrDoc<WasmLang>>;
+type Node<'a> = SgNode<'a, WasmDoc>;
+type NodeMatch<'a> = SgNodeMatch<'a, WasmDoc>;
 
 #[derive(Serialize, Deserialize)]
 pub struct WasmNode {
@@ -26,49 +26,152 @@ pub struct WasmNode {
   pub range: (usize, usize, usize, usize),
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum Severity {
+  Hint,
+  Info,
+  Warning,
+  Error,
+  /// disable the rule
+  Off,
+}
+
+/// Descriptive fields of a rule, reported along with every match.
+#[derive(Clone, Default)]
+pub struct RuleMeta {
+  pub id: Option<String>,
+  pub severity: Option<Severity>,
+  pub message: Option<String>,
+  pub note: Option<String>,
+  pub url: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum LabelStyle {
+  Primary,
+  Secondary,
+}
+
+/// Highlight the node captured by a metavariable, keyed by the variable name.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LabelConfig {
+  pub style: LabelStyle,
+  pub message: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WasmLabel {
+  pub node: WasmNode,
+  pub style: LabelStyle,
+  pub message: Option<String>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct WasmMatch {
   pub node: WasmNode,
   pub env: BTreeMap<String, WasmNode>,
+  // not flattened from RuleMeta: flatten serializes through a map, which becomes an ES Map in JS
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub id: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub severity: Option<Severity>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub message: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub note: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub url: Option<String>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub labels: Vec<WasmLabel>,
+}
+
+impl WasmMatch {
+  pub fn with_meta(self, meta: &RuleMeta) -> Self {
+    let meta = meta.clone();
+    Self {
+      id: meta.id,
+      severity: meta.severity,
+      message: meta.message,
+      note: meta.note,
+      url: meta.url,
+      ..self
+    }
+  }
 }
 
 impl From<NodeMatch<'_>> for WasmMatch {
   fn from(nm: NodeMatch) -> Self {
     let node = nm.get_node().clone();
     let node = WasmNode::from(node);
-    let env = nm.get_env().clone();
-    let env = env_to_map(env);
-    Self { node, env }
+    let env = env_to_map(nm.get_env());
+    Self {
+      node,
+      env,
+      id: None,
+      severity: None,
+      message: None,
+      note: None,
+      url: None,
+      labels: vec![],
+    }
   }
 }
 
-fn env_to_map(env: MetaVarEnv<'_, StrDoc<WasmLang>>) -> BTreeMap<String, WasmNode> {
+/// Labels whose metavariable is not captured in the match are skipped.
+pub fn get_labels(nm: &NodeMatch, labels: &BTreeMap<String, LabelConfig>) -> Vec<WasmLabel> {
+  let env = nm.get_env();
+  let mut ret = vec![];
+  for (name, label) in labels {
+    let node = if let Some(node) = env.get_match(name) {
+      WasmNode::from(node.clone())
+    } else if let Some(node) = join_nodes(&env.get_multiple_matches(name)) {
+      node
+    } else {
+      continue;
+    };
+    ret.push(WasmLabel {
+      node,
+      style: label.style,
+      message: label.message.clone(),
+    });
+  }
+  ret
+}
+
+fn join_nodes(nodes: &[Node]) -> Option<WasmNode> {
+  let (Some(first), Some(last)) = (nodes.first(), nodes.last()) else {
+    return None;
+  };
+  let start = first.start_pos();
+  let end = last.end_pos();
+  let text = nodes.iter().map(|n| n.text()).collect();
+  Some(WasmNode {
+    text,
+    range: (start.0, start.1, end.0, end.1),
+  })
+}
+
+fn env_to_map(env: &MetaVarEnv<'_, WasmDoc>) -> BTreeMap<String, WasmNode> {
   let mut map = BTreeMap::new();
   for id in env.get_matched_variables() {
     match id {
       MetaVariable::Named(name, _) => {
         if let Some(node) = env.get_match(&name) {
           map.insert(name, WasmNode::from(node.clone()));
-        } else if let Some(bytes) = env.get_transformed(&name) {
+        } else if let Some(chars) = env.get_transformed(&name) {
           let node = WasmNode {
-            text: String::from_utf8_lossy(bytes).to_string(),
+            text: chars.iter().collect(),
             range: (0, 0, 0, 0),
           };
-          map.insert(name, WasmNode::from(node));
+          map.insert(name, node);
         }
       }
       MetaVariable::NamedEllipsis(name) => {
         let nodes = env.get_multiple_matches(&name);
-        let (Some(first), Some(last)) = (nodes.first(), nodes.last()) else {
-          continue
-        };
-        let start = first.start_pos();
-        let end = last.end_pos();
-
-        let text = nodes.iter().map(|n| n.text()).collect();
-        let node = WasmNode {
-          text,
-          range: (start.0, start.1, end.0, end.1),
+        let Some(node) = join_nodes(&nodes) else {
+          continue;
         };
         map.insert(name, node);
       }
@@ -1,18 +1,23 @@
 use std::str::FromStr;
 
+use crate::error::WasmError;
+
 use ast_grep_core::language::Language;
 use ast_grep_core::meta_var::MetaVariable;
 use ast_grep_core::replacer::IndentSensitive;
 use ast_grep_core::source::{Content, Doc, Edit, TSParseError};
 use ast_grep_language as L;
 use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::ops::Range;
+use std::path::Path;
 use std::sync::Mutex;
 use tree_sitter as ts;
 use tree_sitter::{InputEdit, Node, Parser, ParserError, Point, Tree};
 use wasm_bindgen::prelude::*;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum WasmLang {
   JavaScript,
   TypeScript,
@@ -39,52 +44,182 @@ impl std::error::Error for NotSupport {}
 
 impl std::fmt::Display for NotSupport {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    write!(f, "Language {} is not supported.", self.0)
+    let names: Vec<_> = WasmLang::ALL.iter().map(|l| l.name()).collect();
+    write!(
+      f,
+      "Language {} is not supported. Supported languages: {}.",
+      self.0,
+      names.join(", ")
+    )
+  }
+}
+
+#[derive(Debug)]
+pub struct IncompatibleVersion {
+  parser_path: String,
+  version: u32,
+}
+
+impl std::error::Error for IncompatibleVersion {}
+
+impl std::fmt::Display for IncompatibleVersion {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "Parser {} uses tree-sitter language ABI version {}, but only versions {} through {} are supported. Please rebuild the parser with a compatible tree-sitter CLI.",
+      self.parser_path, self.version, MIN_COMPATIBLE_LANGUAGE_VERSION, LANGUAGE_VERSION,
+    )
+  }
+}
+
+// must be kept in sync with the web-tree-sitter runtime, i.e. tree-sitter.wasm
+const LANGUAGE_VERSION: u32 = 14;
+const MIN_COMPATIBLE_LANGUAGE_VERSION: u32 = 13;
+
+fn check_version(version: u32, parser_path: &str) -> Result<(), IncompatibleVersion> {
+  if (MIN_COMPATIBLE_LANGUAGE_VERSION..=LANGUAGE_VERSION).contains(&version) {
+    Ok(())
+  } else {
+    Err(IncompatibleVersion {
+      parser_path: parser_path.to_string(),
+      version,
+    })
   }
 }
 
 impl FromStr for WasmLang {
   type Err = NotSupport;
   fn from_str(s: &str) -> Result<Self, Self::Err> {
-    Ok(match s {
-      "javascript" => JavaScript,
-      "typescript" => TypeScript,
+    // accept the same aliases as ast-grep CLI, e.g. `language: JavaScript` in rule file
+    let normalized = s.to_lowercase();
+    Ok(match normalized.as_str() {
+      "javascript" | "js" | "jsx" => JavaScript,
+      "typescript" | "ts" => TypeScript,
       "tsx" => Tsx,
       "c" => C,
-      "csharp" => CSharp,
-      "cpp" => Cpp,
+      "csharp" | "cs" => CSharp,
+      "cpp" | "cc" | "c++" | "cxx" => Cpp,
       "dart" => Dart,
-      "go" => Go,
+      "go" | "golang" => Go,
       "html" => Html,
       "java" => Java,
-      "python" => Python,
-      "rust" => Rust,
+      "python" | "py" => Python,
+      "rust" | "rs" => Rust,
       "scala" => Scala,
       _ => return Err(NotSupport(s.to_string())),
     })
   }
 }
 
+impl WasmLang {
+  pub const ALL: [WasmLang; 13] = [
+    JavaScript, TypeScript, Tsx, C, CSharp, Cpp, Dart, Go, Html, Java, Python, Rust, Scala,
+  ];
+
+  /// The name accepted by `FromStr`, e.g. `javascript`.
+  pub fn name(&self) -> &'static str {
+    match self {
+      JavaScript => "javascript",
+      TypeScript => "typescript",
+      Tsx => "tsx",
+      C => "c",
+      CSharp => "csharp",
+      Cpp => "cpp",
+      Dart => "dart",
+      Go => "go",
+      Html => "html",
+      Java => "java",
+      Python => "python",
+      Rust => "rust",
+      Scala => "scala",
+    }
+  }
+}
+
+/// Guess language from file extension, same as ast-grep-language.
+fn from_extension(path: &Path) -> Option<WasmLang> {
+  Some(match path.extension()?.to_str()? {
+    "c" | "h" => C,
+    "cc" | "hpp" | "cpp" | "c++" | "hh" | "cxx" | "cu" | "ino" => Cpp,
+    "cs" => CSharp,
+    "dart" => Dart,
+    "go" => Go,
+    "html" | "htm" | "xhtml" => Html,
+    "java" => Java,
+    "cjs" | "js" | "mjs" | "jsx" => JavaScript,
+    "py" | "py3" | "pyi" | "bzl" => Python,
+    "rs" => Rust,
+    "scala" | "sc" | "sbt" => Scala,
+    "ts" | "cts" | "mts" => TypeScript,
+    "tsx" => Tsx,
+    _ => return None,
+  })
+}
+
+/// Guess language from the interpreter in shebang, e.g. `#!/usr/bin/env python3`.
+fn from_shebang(src: &str) -> Option<WasmLang> {
+  let line = src.lines().next()?.strip_prefix("#!")?;
+  let mut args = line.split_whitespace();
+  let mut interpreter = args.next()?.rsplit('/').next()?;
+  if interpreter == "env" {
+    // skip env flags like `-S`
+    interpreter = args.find(|arg| !arg.starts_with('-'))?;
+  }
+  // strip version suffix like python3.11
+  let interpreter = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+  Some(match interpreter {
+    "python" | "pypy" => Python,
+    "node" | "nodejs" => JavaScript,
+    "ts-node" | "deno" | "bun" => TypeScript,
+    "scala" => Scala,
+    "dart" => Dart,
+    "rust-script" => Rust,
+    _ => return None,
+  })
+}
+
+/// Detect language by user overrides first, then by file extension, then by shebang line.
+/// Override keys are file names like `Jenkinsfile` or extensions without the dot like `jsm`.
+pub fn detect_language(
+  path: &str,
+  src: &str,
+  overrides: &HashMap<String, WasmLang>,
+) -> Option<WasmLang> {
+  let path = Path::new(path);
+  let name = path.file_name().and_then(|n| n.to_str());
+  let ext = path.extension().and_then(|e| e.to_str());
+  let overridden = [name, ext].iter().flatten().find_map(|k| overrides.get(*k));
+  overridden
+    .copied()
+    .or_else(|| from_extension(path))
+    .or_else(|| from_shebang(src))
+}
+
 static TS_LANG: Mutex<Option<ts::Language>> = Mutex::new(None);
 static LANG: Mutex<WasmLang> = Mutex::new(JavaScript);
 
 impl WasmLang {
   pub async fn set_current(lang: &str, parser_path: &str) -> Result<(), JsError> {
     let lang = WasmLang::from_str(lang)?;
+    setup_parser(parser_path).await?;
     let mut curr_lang = LANG.lock().expect_throw("set language error");
     *curr_lang = lang;
-    setup_parser(parser_path).await?;
     Ok(())
   }
 
-  pub fn get_current() -> Self {
-    *LANG.lock().expect_throw("get language error")
+  /// The language set by `setupParser`, which must have loaded a parser.
+  pub fn get_current() -> Result<Self, WasmError> {
+    if TS_LANG.lock().expect_throw("get language error").is_none() {
+      return Err(WasmError::ParserNotReady);
+    }
+    Ok(*LANG.lock().expect_throw("get language error"))
   }
 }
 
 async fn setup_parser(parser_path: &str) -> Result<(), JsError> {
   let mut parser = ts::Parser::new()?;
   let lang = get_lang(parser_path).await?;
+  check_version(lang.version(), parser_path)?;
   parser.set_language(&lang)?;
   let mut curr_lang = TS_LANG.lock().expect_throw("set language error");
   *curr_lang = Some(lang);
@@ -135,6 +270,10 @@ macro_rules! impl_lang_method {
 }
 
 impl Language for WasmLang {
+  fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+    from_extension(path.as_ref())
+  }
+
   fn get_ts_language(&self) -> ts::Language {
     TS_LANG
       .lock()
@@ -171,11 +310,13 @@ impl Content for Wrapper {
     let start_byte = edit.position;
     let old_end_byte = edit.position + edit.deleted_length;
     let new_end_byte = edit.position + edit.inserted_text.len();
-    let mut input = self.inner.to_vec();
-    let start_position = pos_for_char_offset(&input, start_byte);
-    let old_end_position = pos_for_char_offset(&input, old_end_byte);
-    input.splice(start_byte..old_end_byte, edit.inserted_text.clone());
-    let new_end_position = pos_for_char_offset(&input, new_end_byte);
+    // only scan the prefix once, the end positions continue from the start
+    let start_position = advance(&Point::new(0, 0), &self.inner[..start_byte]);
+    let old_end_position = advance(&start_position, &self.inner[start_byte..old_end_byte]);
+    let new_end_position = advance(&start_position, &edit.inserted_text);
+    self
+      .inner
+      .splice(start_byte..old_end_byte, edit.inserted_text.iter().copied());
     InputEdit::new(
       start_byte as u32,
       old_end_byte as u32,
@@ -185,17 +326,36 @@ impl Content for Wrapper {
       &new_end_position,
     )
   }
+  #[cfg(target_arch = "wasm32")]
   fn get_text<'a>(&'a self, node: &Node) -> Cow<'a, str> {
     // dummy for wasm tree!
     node.utf8_text(&[]).expect("get_text should work")
   }
+  // native tree-sitter does not store text, pick the chars in the utf8 byte range
+  #[cfg(not(target_arch = "wasm32"))]
+  fn get_text<'a>(&'a self, node: &Node) -> Cow<'a, str> {
+    let range = node.byte_range();
+    let (start, end) = (range.start as usize, range.end as usize);
+    let mut offset = 0;
+    let mut text = String::with_capacity(end - start);
+    for &c in &self.inner {
+      if offset >= end {
+        break;
+      }
+      if offset >= start {
+        text.push(c);
+      }
+      offset += c.len_utf8();
+    }
+    Cow::Owned(text)
+  }
   fn get_range(&self, range: Range<usize>) -> &[char] {
     &self.inner[range]
   }
-  fn decode_str(src: &str) -> Cow<[Self::Underlying]> {
+  fn decode_str(src: &str) -> Cow<'_, [Self::Underlying]> {
     Cow::Owned(src.chars().collect())
   }
-  fn encode_bytes(bytes: &[Self::Underlying]) -> Cow<str> {
+  fn encode_bytes(bytes: &[Self::Underlying]) -> Cow<'_, str> {
     Cow::Owned(bytes.iter().collect())
   }
 }
@@ -206,10 +366,10 @@ impl IndentSensitive for Wrapper {
   const NEW_LINE: Self::Underlying = '\n';
 }
 
-fn pos_for_char_offset(input: &[char], offset: usize) -> Point {
-  debug_assert!(offset <= input.len());
-  let (mut row, mut col) = (0, 0);
-  for &c in input.iter().take(offset) {
+/// The position after `input` when it starts at `pos`.
+fn advance(pos: &Point, input: &[char]) -> Point {
+  let (mut row, mut col) = (pos.row(), pos.column());
+  for &c in input {
     if '\n' == c {
       row += 1;
       col = 0;
@@ -224,6 +384,9 @@ fn pos_for_char_offset(input: &[char], offset: usize) -> Point {
 pub struct WasmDoc {
   lang: WasmLang,
   source: Wrapper,
+  timeout: f64,
+  /// tree parsed by `pre_parse`, handed back by the next `parse(None)`
+  tree: RefCell<Option<Tree>>,
 }
 
 impl WasmDoc {
@@ -231,7 +394,25 @@ impl WasmDoc {
     let source = Wrapper {
       inner: src.chars().collect(),
     };
-    Self { source, lang }
+    Self {
+      source,
+      lang,
+      timeout: 0.0,
+      tree: RefCell::new(None),
+    }
+  }
+
+  /// Timeout of every parse in microseconds, zero means no limit.
+  pub fn set_timeout(&mut self, timeout_micros: f64) {
+    self.timeout = timeout_micros.max(0.0);
+  }
+
+  /// Parse the source ahead of `AstGrep::doc` to report parse errors,
+  /// the tree is kept so that `AstGrep::doc` does not parse again.
+  pub fn pre_parse(&self) -> Result<(), TSParseError> {
+    let tree = self.parse(None)?;
+    *self.tree.borrow_mut() = Some(tree);
+    Ok(())
   }
 }
 
@@ -239,9 +420,15 @@ impl Doc for WasmDoc {
   type Lang = WasmLang;
   type Source = Wrapper;
   fn parse(&self, old_tree: Option<&Tree>) -> std::result::Result<Tree, TSParseError> {
+    if old_tree.is_none() {
+      if let Some(tree) = self.tree.borrow_mut().take() {
+        return Ok(tree);
+      }
+    }
     let mut parser = Parser::new()?;
     let ts_lang = self.lang.get_ts_language();
     parser.set_language(&ts_lang)?;
+    parser.set_timeout_micros(self.timeout);
     if let Some(tree) = self.source.parse_tree_sitter(&mut parser, old_tree)? {
       Ok(tree)
     } else {
@@ -255,6 +442,8 @@ impl Doc for WasmDoc {
     &self.source
   }
   fn get_source_mut(&mut self) -> &mut Self::Source {
+    // the cached tree is stale once the source is edited
+    self.tree.get_mut().take();
     &mut self.source
   }
   fn from_str(src: &str, lang: Self::Lang) -> Self {
@@ -263,14 +452,21 @@ impl Doc for WasmDoc {
       source: Wrapper {
         inner: src.chars().collect(),
       },
+      timeout: 0.0,
+      tree: RefCell::new(None),
     }
   }
 }
 
+#[cfg(test)]
+pub fn set_rust_lang() {
+  let mut curr_lang = TS_LANG.lock().expect_throw("set language error");
+  *curr_lang = Some(tree_sitter_rust::language().into());
+}
+
 #[cfg(test)]
 mod test {
   use super::*;
-  use tree_sitter_rust;
 
   // https://github.com/tree-sitter/tree-sitter-rust/issues/82
   // sadly, this does not test what tree-sitter-wasm actually does
@@ -278,11 +474,147 @@ mod test {
   // native tree-sitter can use parse_with_utf16 :(
   #[test]
   fn test_process_pattern() {
-    let mut curr_lang = TS_LANG.lock().expect_throw("set language error");
-    *curr_lang = Some(tree_sitter_rust::language().into());
-    drop(curr_lang);
+    set_rust_lang();
     let grep = WasmLang::Rust.ast_grep("fn test() { Some(123) }");
     let root = grep.root();
     assert!(root.find("Some($A)").is_some());
   }
+
+  #[test]
+  fn test_parse_timeout() {
+    set_rust_lang();
+    let src = "fn test() { let a = Some(123); }\n".repeat(10000);
+    let mut doc = WasmDoc::new(src, WasmLang::Rust);
+    assert!(doc.parse(None).is_ok());
+    doc.set_timeout(1.0);
+    assert!(matches!(
+      doc.parse(None),
+      Err(TSParseError::TreeUnavailable)
+    ));
+  }
+
+  #[test]
+  fn test_pre_parse() {
+    set_rust_lang();
+    let src = "fn test() { let a = Some(123); }\n".repeat(10000);
+    let mut doc = WasmDoc::new(src, WasmLang::Rust);
+    assert!(doc.pre_parse().is_ok());
+    // parsing again would time out, so the tree must come from pre_parse
+    doc.set_timeout(1.0);
+    assert!(doc.parse(None).is_ok());
+    // the kept tree is handed back only once
+    assert!(doc.tree.borrow().is_none());
+    assert!(matches!(
+      doc.parse(None),
+      Err(TSParseError::TreeUnavailable)
+    ));
+  }
+
+  #[test]
+  fn test_check_version() {
+    let version = tree_sitter_rust::language().version() as u32;
+    assert!(check_version(version, "tree-sitter-rust.wasm").is_ok());
+    assert!(check_version(12, "old.wasm").is_err());
+    let err = check_version(15, "new.wasm").expect_err("should fail");
+    let msg = err.to_string();
+    assert!(msg.contains("new.wasm"));
+    assert!(msg.contains("version 15"));
+  }
+
+  #[test]
+  fn test_detect_language() {
+    let overrides = HashMap::new();
+    let detect = |path, src| detect_language(path, src, &overrides).map(|l| l.name());
+    assert_eq!(detect("src/main.rs", ""), Some("rust"));
+    assert_eq!(detect("index.mjs", ""), Some("javascript"));
+    assert_eq!(
+      detect("build", "#!/usr/bin/env python\nprint(1)"),
+      Some("python")
+    );
+    assert_eq!(detect("build", "#!/usr/bin/python3.11\n"), Some("python"));
+    assert_eq!(
+      detect("run", "#!/usr/bin/env -S deno run\n"),
+      Some("typescript")
+    );
+    assert_eq!(detect("run", "#!/bin/node"), Some("javascript"));
+    // extension takes precedence over shebang
+    assert_eq!(detect("a.rs", "#!/usr/bin/env python"), Some("rust"));
+    assert_eq!(detect("build", "#!/bin/sh"), None);
+    assert_eq!(detect("README", "print(1)"), None);
+  }
+
+  #[test]
+  fn test_detect_overrides() {
+    let overrides = HashMap::from([
+      ("Jenkinsfile".to_string(), Java),
+      ("rs".to_string(), Python),
+    ]);
+    let detect = |path, src| detect_language(path, src, &overrides).map(|l| l.name());
+    assert_eq!(detect("ci/Jenkinsfile", ""), Some("java"));
+    // overrides take precedence over both extension and shebang
+    assert_eq!(detect("a.rs", ""), Some("python"));
+    assert_eq!(detect("a.go", "#!/usr/bin/env python"), Some("go"));
+    assert_eq!(detect("a.unknown", "#!/bin/node"), Some("javascript"));
+  }
+
+  #[test]
+  fn test_lang_alias() {
+    let aliases = [
+      ("JavaScript", JavaScript),
+      ("ts", TypeScript),
+      ("c++", Cpp),
+      ("golang", Go),
+      ("py", Python),
+    ];
+    for (alias, lang) in aliases {
+      assert!(WasmLang::from_str(alias).ok() == Some(lang));
+    }
+    assert!(WasmLang::from_str("kotlin").is_err());
+  }
+
+  #[test]
+  fn test_name_round_trip() {
+    for lang in WasmLang::ALL {
+      assert!(WasmLang::from_str(lang.name()).ok() == Some(lang));
+    }
+  }
+
+  #[test]
+  fn test_not_support_message() {
+    let Err(e) = WasmLang::from_str("kotlin") else {
+      panic!("kotlin should not be supported");
+    };
+    let msg = e.to_string();
+    assert!(msg.starts_with("Language kotlin is not supported."));
+    assert!(msg.contains("javascript, typescript, tsx, c, csharp"));
+  }
+
+  #[test]
+  fn test_get_text_non_ascii() {
+    set_rust_lang();
+    let root = ast_grep_core::AstGrep::doc(WasmDoc::new("let a = \"é\"; b;".into(), Rust));
+    let texts: Vec<_> = root
+      .root()
+      .children()
+      .map(|n| n.text().to_string())
+      .collect();
+    assert_eq!(texts, ["let a = \"é\";", "b;"]);
+  }
+
+  #[test]
+  fn test_accept_edit() {
+    let mut source = Wrapper {
+      inner: "a\nbc\nd".chars().collect(),
+    };
+    let edit = Edit::<Wrapper> {
+      position: 3,
+      deleted_length: 3,
+      inserted_text: "x\nyz".chars().collect(),
+    };
+    source.accept_edit(&edit);
+    let text: String = source.inner.iter().collect();
+    assert_eq!(text, "a\nbx\nyz");
+    let pos = advance(&Point::new(1, 1), &edit.inserted_text);
+    assert_eq!((pos.row(), pos.column()), (2, 2));
+  }
 }
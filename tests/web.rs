@@ -2,6 +2,10 @@
 
 #![cfg(target_arch = "wasm32")]
 
+use ast_grep_wasm::utils::{WasmMatch, WasmNode};
+use js_sys::{Map, Reflect};
+use std::collections::BTreeMap;
+use wasm_bindgen::JsCast;
 use wasm_bindgen_test::*;
 
 wasm_bindgen_test_configure!(run_in_browser);
@@ -10,3 +14,36 @@ wasm_bindgen_test_configure!(run_in_browser);
 fn pass() {
   assert_eq!(1 + 1, 2);
 }
+
+fn wasm_node(text: &str) -> WasmNode {
+  WasmNode {
+    text: text.into(),
+    range: (0, 0, 0, text.len()),
+  }
+}
+
+#[wasm_bindgen_test]
+fn match_as_object() {
+  let mut env = BTreeMap::new();
+  env.insert("A".to_string(), wasm_node("a"));
+  let nm = WasmMatch {
+    node: wasm_node("a.unwrap()"),
+    env,
+    id: Some("no-unwrap".into()),
+    severity: None,
+    message: Some("Avoid unwrap".into()),
+    note: None,
+    url: None,
+    labels: vec![],
+  };
+  let ret = serde_wasm_bindgen::to_value(&nm).expect("should serialize");
+  // the playground reads match fields as properties
+  assert!(!ret.is_instance_of::<Map>());
+  let node = Reflect::get(&ret, &"node".into()).expect("should get node");
+  assert!(node.is_object());
+  let id = Reflect::get(&ret, &"id".into()).expect("should get id");
+  assert_eq!(id.as_string().as_deref(), Some("no-unwrap"));
+  // env is still read with map.get
+  let env = Reflect::get(&ret, &"env".into()).expect("should get env");
+  assert!(env.is_instance_of::<Map>());
+}